@@ -0,0 +1,122 @@
+// Boot-Konfiguration fuer Kalibrierungswerte und Pin-/Kanalbelegung. Wird aus einer einfachen
+// `key=value`-Datei geladen, damit ein Servo-Recalibrieren oder Pin-Umverdrahten ein Datei-Edit
+// auf der SD-Karte ist statt ein Rebuild.
+use log::warn;
+use std::env;
+use std::fs;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RobotConfig {
+    pub freq: f32,
+
+    pub front_left_pulse: u16,
+    pub front_right_pulse: u16,
+    pub back_left_pulse: u16,
+    pub back_right_pulse: u16,
+
+    pub motor_channel: u8,
+    pub front_steering_channel: u8,
+    pub back_steering_channel: u8,
+
+    pub neutral_pulse: f32,
+    pub dead_zone: i64,
+
+    pub horn_pin: u8,
+    pub lights_pin: u8,
+
+    pub failsafe_timeout_ms: u64,
+}
+
+impl Default for RobotConfig {
+    fn default() -> Self {
+        RobotConfig {
+            freq: 200.0, // 200 Hz
+
+            front_left_pulse: 1150,
+            front_right_pulse: 305,
+            back_left_pulse: 1375,
+            back_right_pulse: 2185,
+
+            motor_channel: 0,
+            front_steering_channel: 1,
+            back_steering_channel: 2,
+
+            neutral_pulse: 1450.0,
+            dead_zone: 7,
+
+            horn_pin: 23,
+            lights_pin: 24,
+
+            failsafe_timeout_ms: 500,
+        }
+    }
+}
+
+// Laedt die Konfiguration aus der Datei, die per `ROBOT_CONFIG_PATH` angegeben ist (Standard:
+// "robot.conf"). Fehlt die Datei oder laesst sie sich nicht lesen, werden die Standardwerte
+// verwendet, damit das Fehlen einer Konfiguration nicht den Boot verhindert.
+pub fn load_config() -> RobotConfig {
+    let path = env::var("ROBOT_CONFIG_PATH").unwrap_or_else(|_| "robot.conf".to_string());
+    load_config_from_file(&path)
+}
+
+fn load_config_from_file(path: &str) -> RobotConfig {
+    let mut config = RobotConfig::default();
+
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) => {
+            warn!(
+                "[Config] Konnte Konfigurationsdatei '{}' nicht lesen ({}), verwende Standardwerte.",
+                path, e
+            );
+            return config;
+        }
+    };
+
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            warn!("[Config] Ungueltige Zeile ignoriert (erwarte key=value): {}", line);
+            continue;
+        };
+        let key = key.trim();
+        let value = value.trim();
+
+        match key {
+            "freq" => apply(&mut config.freq, key, value),
+            "front_left_pulse" => apply(&mut config.front_left_pulse, key, value),
+            "front_right_pulse" => apply(&mut config.front_right_pulse, key, value),
+            "back_left_pulse" => apply(&mut config.back_left_pulse, key, value),
+            "back_right_pulse" => apply(&mut config.back_right_pulse, key, value),
+            "motor_channel" => apply(&mut config.motor_channel, key, value),
+            "front_steering_channel" => apply(&mut config.front_steering_channel, key, value),
+            "back_steering_channel" => apply(&mut config.back_steering_channel, key, value),
+            "neutral_pulse" => apply(&mut config.neutral_pulse, key, value),
+            "dead_zone" => apply(&mut config.dead_zone, key, value),
+            "horn_pin" => apply(&mut config.horn_pin, key, value),
+            "lights_pin" => apply(&mut config.lights_pin, key, value),
+            "failsafe_timeout_ms" => apply(&mut config.failsafe_timeout_ms, key, value),
+            _ => warn!("[Config] Unbekannter Schluessel '{}' ignoriert.", key),
+        }
+    }
+
+    config
+}
+
+// Parst `value` in das Feld `field`; bei einem Parse-Fehler bleibt der Default-Wert erhalten und
+// es wird nur gewarnt, damit eine teilweise ausgefuellte Datei trotzdem bootet.
+fn apply<T: FromStr>(field: &mut T, key: &str, value: &str) {
+    match value.parse::<T>() {
+        Ok(parsed) => *field = parsed,
+        Err(_) => warn!(
+            "[Config] Konnte Wert fuer '{}' nicht parsen ('{}'), behalte Standardwert.",
+            key, value
+        ),
+    }
+}
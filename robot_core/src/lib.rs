@@ -1,35 +1,78 @@
 use log::{error, info, warn};
 use pca9685_rppal::Pca9685;
-use robot_web::CommandPayload;
+use robot_web::{CommandPayload, MqttStatePublisher, RobotState};
 use rppal::gpio::Gpio;
-use std::sync::mpsc;
+use std::env;
+use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
 
-const FREQ: f32 = 200.0; // 50 Hz
-
-const FRONT_LEFT_PULSE: u16 = 1150;
-const FRONT_RIGHT_PULSE: u16 = 305;
-
-const BACK_LEFT_PULSE: u16 = 1375;
-const BACK_RIGHT_PULSE: u16 = 2185;
-const MOTOR_CHANNEL: u8 = 0;
-const FRONT_STEERING_CHANNEL: u8 = 1;
-const BACK_STEERING_CHANNEL: u8 = 2;
+mod config;
+pub use config::RobotConfig;
 
 pub fn main() {
     env_logger::init();
 
+    let config = config::load_config();
+    info!("[Main Thread] Konfiguration geladen: {:?}", config);
+
     let (tx, rx) = mpsc::channel::<CommandPayload>();
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    // Eigener Receiver fuer die Hauptschleife unten: der Shutdown darf NICHT ueber den
+    // `CommandPayload`-Kanal laufen, den `/command`, `/ws` und die MQTT-Bruecke ebenfalls fuettern,
+    // sonst koennte ein beliebiger Client per {"command":"shutdown"} die Hauptschleife beenden.
+    let main_shutdown_rx = shutdown_rx.clone();
+    let robot_state: Arc<Mutex<RobotState>> = Arc::new(Mutex::new(RobotState::default()));
 
     let server_tx_clone = tx.clone();
+    let server_robot_state = robot_state.clone();
     let server_thread_handle = thread::spawn(move || {
         let host = "0.0.0.0".to_string();
         let port = 8080;
-        robot_web::start_axum_server_in_thread(host, port, server_tx_clone);
+        robot_web::start_axum_server_in_thread(
+            host,
+            port,
+            server_tx_clone,
+            shutdown_rx,
+            server_robot_state,
+        );
     });
 
     info!("Webserver wird in einem separaten Thread gestartet. Hauptthread lauscht auf Befehle...");
 
+    // Ctrl-C/SIGTERM: setzt ausschliesslich den Shutdown-Kanal, den sowohl die Hauptschleife als
+    // auch `run_axum_server`'s `with_graceful_shutdown` beobachten. Absichtlich kein Weg ueber den
+    // `CommandPayload`-Kanal, da der von unauthentifizierten Transporten (HTTP/WS/MQTT) gefuettert
+    // wird.
+    if let Err(e) = ctrlc::set_handler(move || {
+        warn!("[Main Thread] Shutdown-Signal empfangen. Fahre sauber herunter...");
+        let _ = shutdown_tx.send(true);
+    }) {
+        error!("[Main Thread] Konnte Signal-Handler nicht installieren: {}", e);
+    }
+
+    // Optionale MQTT-Bruecke: nur aktiv, wenn ROBOT_MQTT_BROKER gesetzt ist (z.B.
+    // "mqtt://broker.local:1883/robot1"), damit lokale HTTP-Steuerung unveraendert funktioniert.
+    let mqtt_state_publisher = match env::var("ROBOT_MQTT_BROKER") {
+        Ok(broker_url) => {
+            let mqtt_tx_clone = tx.clone();
+            robot_web::start_mqtt_client_in_thread(broker_url, mqtt_tx_clone)
+        }
+        Err(_) => None,
+    };
+
+    // Optionaler Relay-Client: nur aktiv, wenn ROBOT_RELAY_URL (plus ROBOT_ID/ROBOT_RELAY_TOKEN)
+    // gesetzt ist, damit lokale und relayte Steuerung nebeneinander bestehen koennen.
+    if let Ok(relay_url) = env::var("ROBOT_RELAY_URL") {
+        let robot_id = env::var("ROBOT_ID").unwrap_or_else(|_| "robot".to_string());
+        let relay_token = env::var("ROBOT_RELAY_TOKEN").unwrap_or_default();
+        let relay_tx_clone = tx.clone();
+        thread::spawn(move || {
+            robot_web::start_relay_client_in_thread(relay_url, robot_id, relay_token, relay_tx_clone);
+        });
+    }
+
     let controller_res = Pca9685::new();
     if controller_res.is_err() {
         error!("[Main Thread] Motor Controller not initialized (perhaps not plugged in?!)");
@@ -41,15 +84,15 @@ pub fn main() {
     let mut controller = controller_res.unwrap();
     controller.init().unwrap();
 
-    if let Err(e) = controller.set_pwm_freq(FREQ) {
+    if let Err(e) = controller.set_pwm_freq(config.freq) {
         error!("[Main Thread] Failed to set PWM frequency: {:?}", e);
         panic!("[Main Thread] PWM frequency setting failed.");
     }
 
     let gpio_res = Gpio::new();
     let (mut horn, mut lights) = if let Ok(gpio) = gpio_res {
-        let horn_pin = gpio.get(23).map(|p| p.into_output());
-        let lights_pin = gpio.get(24).map(|p| p.into_output());
+        let horn_pin = gpio.get(config.horn_pin).map(|p| p.into_output());
+        let lights_pin = gpio.get(config.lights_pin).map(|p| p.into_output());
 
         if horn_pin.is_err() || lights_pin.is_err() {
             error!("[Main Thread] Failed to initialize GPIO pins.");
@@ -62,25 +105,55 @@ pub fn main() {
         (None, None)
     };
 
-    loop {
-        match rx.recv() {
+    // Wie lange der Hauptthread ohne neuen speed/direction/heartbeat-Befehl wartet, bevor der
+    // Motor automatisch auf neutral gefahren wird (Totmann-Schalter bei Verbindungsabbruch).
+    let failsafe_timeout = Duration::from_millis(config.failsafe_timeout_ms);
+    // Abfrageintervall fuer `recv_timeout`: muss kuerzer als `failsafe_timeout` sein, damit die
+    // Staleness-Pruefung unten auch dann regelmaessig laeuft, wenn der Kanal nie leerlaeuft (z.B.
+    // ein Client, der staendig `turbo`/`horn` sendet, ohne je `speed`/`direction` zu aktualisieren).
+    let poll_interval = std::cmp::min(failsafe_timeout, Duration::from_millis(100));
+
+    let mut last_command_at = Instant::now();
+    let mut failsafe_active = false;
+
+    'recv_loop: loop {
+        if *main_shutdown_rx.borrow() {
+            info!("[Main Thread] Shutdown angefordert. Beende Empfangs-Loop.");
+            break 'recv_loop;
+        }
+
+        match rx.recv_timeout(poll_interval) {
             Ok(command_payload) => {
                 info!(
                     "[Main Thread] Befehl vom Server empfangen: {:?}",
                     command_payload
                 );
+
+                if matches!(
+                    command_payload.command.as_str(),
+                    "speed" | "direction" | "heartbeat"
+                ) {
+                    last_command_at = Instant::now();
+                    if failsafe_active {
+                        info!("[Main Thread] Failsafe aufgehoben, Befehle treffen wieder ein.");
+                        failsafe_active = false;
+                    }
+                }
+
                 match command_payload.command.as_str() {
                     "speed" => {
                         if let Some(s) = command_payload.value.as_i64() {
                             info!("[Main Thread] Successfully received speed value: {}", s);
-                            let pulse = speed_to_pulse(s, turbo);
+                            let pulse = speed_to_pulse(s, turbo, &config);
                             info!(
                                 "[Main Thread] Setting motor (channel {}) pulse to: {}",
-                                MOTOR_CHANNEL, pulse
+                                config.motor_channel, pulse
                             );
-                            if let Err(e) = controller.set_pwm(MOTOR_CHANNEL, 0, pulse) {
+                            if let Err(e) = controller.set_pwm(config.motor_channel, 0, pulse) {
                                 error!("[Main Thread] Failed to set motor PWM: {:?}", e);
                             }
+                            publish_state(&mqtt_state_publisher, "speed", s);
+                            update_robot_state(&robot_state, |state| state.set_speed(s));
                         } else {
                             warn!("[Main Thread] No speed value provided");
                         }
@@ -88,7 +161,7 @@ pub fn main() {
                     "direction" => {
                         if let Some(d) = command_payload.value.as_i64() {
                             info!("[Main Thread] Successfully received direction value: {}", d);
-                            let (front_pulse, back_pulse) = direction_to_pulse(d);
+                            let (front_pulse, back_pulse) = direction_to_pulse(d, &config);
                             info!(
                                 "[Main Thread] Calculated pulses - Front: {}, Back: {}",
                                 front_pulse, back_pulse
@@ -96,22 +169,26 @@ pub fn main() {
 
                             info!(
                                 "[Main Thread] Setting front steering (channel {}) pulse to: {}",
-                                FRONT_STEERING_CHANNEL, front_pulse
+                                config.front_steering_channel, front_pulse
                             );
                             if let Err(e) =
-                                controller.set_pwm(FRONT_STEERING_CHANNEL, 0, front_pulse)
+                                controller.set_pwm(config.front_steering_channel, 0, front_pulse)
                             {
                                 error!("[Main Thread] Failed to set front steering PWM: {:?}", e);
                             }
 
                             info!(
                                 "[Main Thread] Setting back steering (channel {}) pulse to: {}",
-                                BACK_STEERING_CHANNEL, back_pulse
+                                config.back_steering_channel, back_pulse
                             );
-                            if let Err(e) = controller.set_pwm(BACK_STEERING_CHANNEL, 0, back_pulse)
+                            if let Err(e) = controller.set_pwm(config.back_steering_channel, 0, back_pulse)
                             {
                                 error!("[Main Thread] Failed to set back steering PWM: {:?}", e);
                             }
+                            publish_state(&mqtt_state_publisher, "direction", d);
+                            update_robot_state(&robot_state, |state| {
+                                state.set_direction(d, front_pulse, back_pulse)
+                            });
                         } else {
                             warn!("[Main Thread] No direction value provided");
                         }
@@ -131,6 +208,8 @@ pub fn main() {
                             } else {
                                 warn!("[Main Thread] Headlights pin not available.");
                             }
+                            publish_state(&mqtt_state_publisher, "headlights", h);
+                            update_robot_state(&robot_state, |state| state.set_headlights(h));
                         } else {
                             warn!("[Main Thread] No headlights value provided");
                         }
@@ -147,28 +226,34 @@ pub fn main() {
                             } else {
                                 warn!("[Main Thread] Horn pin not available.");
                             }
+                            publish_state(&mqtt_state_publisher, "horn", h);
+                            update_robot_state(&robot_state, |state| state.set_horn(h));
                         } else {
                             warn!("[Main Thread] No horn value provided");
                         }
                     }
+                    "heartbeat" => {
+                        info!("[Main Thread] Heartbeat empfangen.");
+                    }
                     "turbo" => {
                         if let Some(t) = command_payload.value.as_bool() {
                             info!("[Main Thread] Successfully received turbo value: {}", t);
                             turbo = t;
+                            update_robot_state(&robot_state, |state| state.set_turbo(t));
                         } else {
                             warn!("[Main Thread] No turbo value provided");
                         }
                     }
                     "calibrate" => {
                         info!("[Main Thread] Calibrate command received. Setting steering to neutral.");
-                        let (front_neutral, back_neutral) = direction_to_pulse(0);
+                        let (front_neutral, back_neutral) = direction_to_pulse(0, &config);
                         controller
-                            .set_pwm(FRONT_STEERING_CHANNEL, 0, front_neutral)
+                            .set_pwm(config.front_steering_channel, 0, front_neutral)
                             .unwrap_or_else(|e| {
                                 error!("Failed to set front neutral: {:?}", e);
                             });
                         controller
-                            .set_pwm(BACK_STEERING_CHANNEL, 0, back_neutral)
+                            .set_pwm(config.back_steering_channel, 0, back_neutral)
                             .unwrap_or_else(|e| {
                                 error!("Failed to set back neutral: {:?}", e);
                             });
@@ -176,6 +261,9 @@ pub fn main() {
                             "[Main Thread] Steering set to neutral: Front {}, Back {}",
                             front_neutral, back_neutral
                         );
+                        update_robot_state(&robot_state, |state| {
+                            state.set_direction(0, front_neutral, back_neutral)
+                        });
                     }
                     _ => {
                         warn!(
@@ -185,14 +273,30 @@ pub fn main() {
                     }
                 }
             }
-            Err(e) => {
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
                 error!(
-                    "[Main Thread] Fehler beim Empfangen vom Kanal (Server vermutlich beendet): {}",
-                    e
+                    "[Main Thread] Fehler beim Empfangen vom Kanal (Server vermutlich beendet): Kanal getrennt."
                 );
                 break;
             }
         }
+
+        // Laeuft bei jedem Schleifendurchlauf (nicht nur bei `Err(Timeout)`), damit ein Client,
+        // der den Kanal mit anderen Befehlen als speed/direction/heartbeat am Leben haelt, die
+        // Staleness-Pruefung nicht auf unbestimmte Zeit verhindern kann.
+        if !failsafe_active && last_command_at.elapsed() >= failsafe_timeout {
+            warn!(
+                "[Main Thread] Keine speed/direction/heartbeat-Befehle seit {:?} empfangen. Aktiviere Failsafe: Motor auf neutral.",
+                last_command_at.elapsed()
+            );
+            if let Err(e) = controller.set_pwm(config.motor_channel, 0, speed_to_pulse(0, false, &config)) {
+                error!("[Main Thread] Failed to set motor PWM during failsafe: {:?}", e);
+            }
+            publish_state(&mqtt_state_publisher, "speed", 0);
+            update_robot_state(&robot_state, |state| state.set_speed(0));
+            failsafe_active = true;
+        }
     }
 
     warn!("[Main Thread] Empfangs-Loop beendet. Warte auf Beendigung des Server-Threads...");
@@ -201,10 +305,10 @@ pub fn main() {
     }
 
     info!("[Main Thread] Setting outputs to neutral/off before exit.");
-    let (front_neutral, back_neutral) = direction_to_pulse(0);
-    let _ = controller.set_pwm(FRONT_STEERING_CHANNEL, 0, front_neutral);
-    let _ = controller.set_pwm(BACK_STEERING_CHANNEL, 0, back_neutral);
-    let _ = controller.set_pwm(MOTOR_CHANNEL, 0, speed_to_pulse(0, false));
+    let (front_neutral, back_neutral) = direction_to_pulse(0, &config);
+    let _ = controller.set_pwm(config.front_steering_channel, 0, front_neutral);
+    let _ = controller.set_pwm(config.back_steering_channel, 0, back_neutral);
+    let _ = controller.set_pwm(config.motor_channel, 0, speed_to_pulse(0, false, &config));
     if let Some(pin) = lights.as_mut() {
         pin.set_low();
     }
@@ -215,21 +319,39 @@ pub fn main() {
     info!("[Main Thread] Anwendung wird beendet.");
 }
 
-fn speed_to_pulse(speed: i64, turbo: bool) -> u16 {
+// Meldet einen angewendeten Befehl ueber die optionale MQTT-Bruecke unter `<prefix>/state/<key>`
+// zurueck. No-op, solange kein Broker konfiguriert ist.
+fn publish_state(publisher: &Option<MqttStatePublisher>, key: &str, value: impl ToString) {
+    if let Some(publisher) = publisher {
+        publisher.publish_state(key, value);
+    }
+}
+
+// Wendet `f` auf den gemeinsamen `RobotState` an, den `/status` ausliefert. Ein vergifteter Mutex
+// (Panic waehrend eines Locks) wird wie bei `status_handler` toleriert statt den Hauptthread
+// abzureissen.
+fn update_robot_state(state: &Arc<Mutex<RobotState>>, f: impl FnOnce(&mut RobotState)) {
+    match state.lock() {
+        Ok(mut guard) => f(&mut guard),
+        Err(poisoned) => f(&mut poisoned.into_inner()),
+    }
+}
+
+fn speed_to_pulse(speed: i64, turbo: bool, config: &RobotConfig) -> u16 {
     let x = speed.clamp(-100, 100);
 
-    const NEUTRAL_PULSE: f32 = 1450.0;
-    const DEAD_ZONE: i64 = 7;
+    let neutral_pulse = config.neutral_pulse;
+    let dead_zone = config.dead_zone;
 
-    if (-DEAD_ZONE..=DEAD_ZONE).contains(&x) {
-        NEUTRAL_PULSE as u16
-    } else if x < -DEAD_ZONE {
-        let slope = 200.0 / (100.0 - (DEAD_ZONE as f32 + 1.0));
-        let pulse_val = NEUTRAL_PULSE + (x as f32 + DEAD_ZONE as f32) * slope;
+    if (-dead_zone..=dead_zone).contains(&x) {
+        neutral_pulse as u16
+    } else if x < -dead_zone {
+        let slope = 200.0 / (100.0 - (dead_zone as f32 + 1.0));
+        let pulse_val = neutral_pulse + (x as f32 + dead_zone as f32) * slope;
         pulse_val.round() as u16
     } else {
-        let slope = 750.0 / (100.0 - (DEAD_ZONE as f32 + 1.0));
-        let pulse_val = NEUTRAL_PULSE + (x as f32 - DEAD_ZONE as f32) * slope;
+        let slope = 750.0 / (100.0 - (dead_zone as f32 + 1.0));
+        let pulse_val = neutral_pulse + (x as f32 - dead_zone as f32) * slope;
         let mut final_pulse = pulse_val.round() as u16;
         if turbo {
             final_pulse = final_pulse.saturating_add(100);
@@ -238,16 +360,16 @@ fn speed_to_pulse(speed: i64, turbo: bool) -> u16 {
     }
 }
 
-fn direction_to_pulse(direction: i64) -> (u16, u16) {
+fn direction_to_pulse(direction: i64, config: &RobotConfig) -> (u16, u16) {
     let x = direction.clamp(-100, 100) as f32;
 
     let normalized_direction = (x + 100.0) / 200.0;
 
-    let front_pulse = FRONT_LEFT_PULSE as f32 * (1.0 - normalized_direction)
-        + FRONT_RIGHT_PULSE as f32 * normalized_direction;
+    let front_pulse = config.front_left_pulse as f32 * (1.0 - normalized_direction)
+        + config.front_right_pulse as f32 * normalized_direction;
 
-    let back_pulse = BACK_LEFT_PULSE as f32 * (1.0 - normalized_direction)
-        + BACK_RIGHT_PULSE as f32 * normalized_direction;
+    let back_pulse = config.back_left_pulse as f32 * (1.0 - normalized_direction)
+        + config.back_right_pulse as f32 * normalized_direction;
 
     (front_pulse.round() as u16, back_pulse.round() as u16)
 }
@@ -1,14 +1,24 @@
 use axum::{
+    extract::ws::{Message, WebSocket, WebSocketUpgrade},
     extract::State,
     http::StatusCode,
     response::IntoResponse,
     routing::{get, post},
     Json, Router,
 };
+use futures_util::{SinkExt, StreamExt};
 use log::{error, info, warn};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::net::SocketAddr;
-use std::sync::mpsc;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::watch;
+
+mod mqtt;
+pub use mqtt::{start_mqtt_client_in_thread, MqttStatePublisher};
+
+mod relay;
+pub use relay::start_relay_client_in_thread;
 
 // Diese Struktur wird über den Channel gesendet und als JSON empfangen/gesendet.
 // Sie muss `Clone` sein, für den State in Axum und das Senden über den Channel.
@@ -19,16 +29,78 @@ pub struct CommandPayload {
     pub value: serde_json::Value,
 }
 
-// AppState für den Axum-Server, hält den Sender des MPSC-Kanals.
+// Letzter vom Hauptthread angewendeter Zustand: Sollwerte, berechnete Pulse und Zeitpunkt der
+// letzten Anwendung. Wird bei jedem verarbeiteten Befehl aktualisiert und unveraendert als JSON
+// an `/status` ausgeliefert, damit ein Operator auch bei reiner Fernsteuerung sehen kann, was das
+// Fahrzeug tatsaechlich gerade macht.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RobotState {
+    pub speed: Option<i64>,
+    pub direction: Option<i64>,
+    pub front_pulse: Option<u16>,
+    pub back_pulse: Option<u16>,
+    pub turbo: bool,
+    pub horn: bool,
+    pub headlights: bool,
+    pub last_command_at_unix_ms: Option<u128>,
+}
+
+impl RobotState {
+    // Aktualisiert den Zeitstempel der letzten Anwendung; wird von jedem der `set_*`-Helfer
+    // unten aufgerufen, damit der Aufrufer es nicht selbst tun muss.
+    fn touch(&mut self) {
+        self.last_command_at_unix_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|d| d.as_millis());
+    }
+
+    pub fn set_speed(&mut self, speed: i64) {
+        self.speed = Some(speed);
+        self.touch();
+    }
+
+    pub fn set_direction(&mut self, direction: i64, front_pulse: u16, back_pulse: u16) {
+        self.direction = Some(direction);
+        self.front_pulse = Some(front_pulse);
+        self.back_pulse = Some(back_pulse);
+        self.touch();
+    }
+
+    pub fn set_turbo(&mut self, turbo: bool) {
+        self.turbo = turbo;
+        self.touch();
+    }
+
+    pub fn set_horn(&mut self, horn: bool) {
+        self.horn = horn;
+        self.touch();
+    }
+
+    pub fn set_headlights(&mut self, headlights: bool) {
+        self.headlights = headlights;
+        self.touch();
+    }
+}
+
+/// Von mehreren Threads gemeinsam gehaltener `RobotState` (Hauptthread schreibt, `/status` liest).
+pub type SharedRobotState = Arc<Mutex<RobotState>>;
+
+// AppState für den Axum-Server, hält den Sender des MPSC-Kanals und den gemeinsamen Robot-Zustand.
 // Muss Clone implementieren, damit Axum es für jeden Request klonen kann.
 #[derive(Clone)]
 struct AppState {
     command_tx: mpsc::Sender<CommandPayload>,
+    robot_state: SharedRobotState,
 }
 
-async fn status_handler() -> impl IntoResponse {
+async fn status_handler(State(state): State<AppState>) -> impl IntoResponse {
     info!("GET /status aufgerufen");
-    (StatusCode::OK, "Server is running with Axum!")
+    let snapshot = match state.robot_state.lock() {
+        Ok(guard) => guard.clone(),
+        Err(poisoned) => poisoned.into_inner().clone(),
+    };
+    (StatusCode::OK, Json(snapshot))
 }
 
 async fn command_handler(
@@ -63,18 +135,121 @@ async fn command_handler(
     }
 }
 
+// Nimmt eine WebSocket-Upgrade-Anfrage entgegen und reicht die Verbindung an `handle_ws_socket` weiter.
+async fn ws_handler(
+    ws: WebSocketUpgrade,
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    ws.on_upgrade(move |socket| handle_ws_socket(socket, state))
+}
+
+// Hält die Verbindung für die Dauer der Session offen: jeder eingehende Text-Frame
+// wird wie `command_handler` als `CommandPayload` interpretiert und über denselben
+// Kanal an den Hauptthread weitergereicht. Auf jeden erfolgreich weitergeleiteten
+// Befehl folgt ein JSON-Ack-Frame auf demselben Socket.
+async fn handle_ws_socket(socket: WebSocket, state: AppState) {
+    info!("WebSocket-Verbindung auf /ws geöffnet");
+    let (mut sender, mut receiver) = socket.split();
+
+    while let Some(msg) = receiver.next().await {
+        let msg = match msg {
+            Ok(msg) => msg,
+            Err(e) => {
+                warn!("WebSocket-Fehler beim Empfangen: {}", e);
+                break;
+            }
+        };
+
+        let text = match msg {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+
+        let payload: CommandPayload = match serde_json::from_str(&text) {
+            Ok(payload) => payload,
+            Err(e) => {
+                warn!("Konnte WebSocket-Frame nicht als CommandPayload deserialisieren: {}", e);
+                let ack = serde_json::json!({
+                    "status": "error_invalid_payload",
+                    "error": e.to_string(),
+                });
+                if sender.send(Message::Text(ack.to_string())).await.is_err() {
+                    break;
+                }
+                continue;
+            }
+        };
+
+        info!("WebSocket-Befehl empfangen: {:?}", payload);
+
+        let ack = match state.command_tx.send(payload.clone()) {
+            Ok(_) => serde_json::json!({
+                "status": "command_received_and_forwarded",
+                "command": payload.command,
+                "value": payload.value,
+            }),
+            Err(e) => {
+                error!("Fehler beim Senden des WebSocket-Befehls an den Hauptthread: {}", e);
+                serde_json::json!({
+                    "status": "error_forwarding_command",
+                    "error": format!("Konnte Befehl nicht intern weiterleiten: {}", e),
+                })
+            }
+        };
+
+        if sender.send(Message::Text(ack.to_string())).await.is_err() {
+            break;
+        }
+    }
+
+    info!("WebSocket-Verbindung auf /ws geschlossen");
+}
+
+// Wartet, bis der uebergebene Shutdown-Kanal auf `true` gesetzt wird, und gibt die Kontrolle dann
+// an `with_graceful_shutdown` zurueck, damit der Listener aufhoert neue Verbindungen anzunehmen.
+async fn shutdown_signal(mut shutdown_rx: watch::Receiver<bool>) {
+    loop {
+        if *shutdown_rx.borrow() {
+            return;
+        }
+        if shutdown_rx.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
+// Maximale Zeit, die nach einem Shutdown-Signal auf offene Verbindungen (insbesondere
+// lang laufende `/ws`-Sockets) gewartet wird, bevor der Server-Thread erzwungen beendet wird.
+const GRACEFUL_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Wartet auf das Shutdown-Signal und schlaeft danach noch `GRACEFUL_SHUTDOWN_TIMEOUT`, bevor sie
+// zurueckkehrt. Gewinnt diese Future das Rennen gegen `axum::serve`, wird dessen Future in
+// `run_axum_server` fallen gelassen und damit auch noch offene Verbindungen (z.B. ein `/ws`-Client,
+// der nie ein Close-Frame schickt) zwangsweise beendet, statt den Server-Thread unbegrenzt zu blockieren.
+async fn shutdown_grace_period_elapsed(shutdown_rx: watch::Receiver<bool>) {
+    shutdown_signal(shutdown_rx).await;
+    tokio::time::sleep(GRACEFUL_SHUTDOWN_TIMEOUT).await;
+}
+
 pub async fn run_axum_server(
     host: String,
     port: u16,
     command_tx: mpsc::Sender<CommandPayload>,
+    shutdown_rx: watch::Receiver<bool>,
+    robot_state: SharedRobotState,
 ) -> Result<(), Box<dyn std::error::Error>> {
     // Box<dyn Error> für generisches Fehlerhandling
-    let app_state = AppState { command_tx };
+    let app_state = AppState {
+        command_tx,
+        robot_state,
+    };
 
     // Definiere die Routen
     let app = Router::new()
         .route("/status", get(status_handler))
         .route("/command", post(command_handler))
+        .route("/ws", get(ws_handler))
         .with_state(app_state); // Den State für alle Handler verfügbar machen
 
     let addr_str = format!("{}:{}", host, port);
@@ -84,7 +259,26 @@ pub async fn run_axum_server(
 
     // Server mit tokio::net::TcpListener starten
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app.into_make_service()).await?; // .into_make_service() ist oft nötig
+    let grace_shutdown_rx = shutdown_rx.clone();
+    let serve_future = axum::serve(listener, app.into_make_service())
+        .with_graceful_shutdown(shutdown_signal(shutdown_rx));
+
+    // `with_graceful_shutdown` allein wartet unbegrenzt auf bereits offene Verbindungen; ein
+    // Client, der `/ws` offen haelt ohne je zu schliessen, wuerde den Server-Thread (und damit
+    // `server_thread_handle.join()` im Hauptthread) fuer immer blockieren. Das Rennen gegen die
+    // Kulanzfrist stellt sicher, dass der Server-Thread in jedem Fall beendet wird.
+    tokio::select! {
+        result = serve_future => {
+            result?;
+            info!("Axum Webserver hat alle Verbindungen sauber beendet.");
+        }
+        _ = shutdown_grace_period_elapsed(grace_shutdown_rx) => {
+            warn!(
+                "Axum Webserver: Kulanzfrist von {:?} nach Shutdown-Signal abgelaufen, beende trotz offener Verbindungen.",
+                GRACEFUL_SHUTDOWN_TIMEOUT
+            );
+        }
+    }
 
     Ok(())
 }
@@ -95,6 +289,8 @@ pub fn start_axum_server_in_thread(
     host: String,
     port: u16,
     command_tx: mpsc::Sender<CommandPayload>,
+    shutdown_rx: watch::Receiver<bool>,
+    robot_state: SharedRobotState,
 ) {
     info!("Erstelle neuen Thread für Axum Webserver...");
 
@@ -113,7 +309,7 @@ pub fn start_axum_server_in_thread(
     };
 
     rt.block_on(async {
-        if let Err(e) = run_axum_server(host, port, command_tx).await {
+        if let Err(e) = run_axum_server(host, port, command_tx, shutdown_rx, robot_state).await {
             error!("Axum Webserver-Fehler: {}", e);
         }
     });
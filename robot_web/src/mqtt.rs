@@ -0,0 +1,144 @@
+// Optionale MQTT-Bruecke: alternative zum Axum-HTTP-Server, fuer Flotten- und
+// Home-Automation-Szenarien, bei denen ein Broker ohnehin schon vorhanden ist.
+use crate::CommandPayload;
+use log::{error, info, warn};
+use rumqttc::{Client, Event, MqttOptions, Packet, QoS};
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Handle, mit dem der Hauptthread Zustandsaenderungen unter `<prefix>/state/<key>` publizieren
+/// kann, ohne die Verbindungs- oder Abonnement-Details des MQTT-Clients kennen zu muessen.
+#[derive(Clone)]
+pub struct MqttStatePublisher {
+    client: Client,
+    prefix: String,
+}
+
+impl MqttStatePublisher {
+    // Nutzt `try_publish` statt `publish`: Letzteres blockiert, sobald die interne
+    // Request-Queue (Kapazitaet 10, siehe `Client::new` unten) voll ist, z.B. weil der Broker
+    // langsam oder nicht erreichbar ist. Da `publish_state` synchron aus der Hauptschleife
+    // aufgerufen wird, wuerde ein haengender Broker damit auch PWM-Updates, die
+    // Failsafe-Neutralstellung und den Shutdown-Pfad blockieren. `try_publish` gibt stattdessen
+    // sofort einen Fehler zurueck und der Zustandsupdate wird verworfen.
+    pub fn publish_state(&self, key: &str, value: impl ToString) {
+        let topic = format!("{}/state/{}", self.prefix, key);
+        if let Err(e) = self
+            .client
+            .try_publish(&topic, QoS::AtLeastOnce, true, value.to_string())
+        {
+            warn!(
+                "[MQTT] Zustand auf {} verworfen (Queue voll oder Verbindung getrennt): {}",
+                topic, e
+            );
+        }
+    }
+}
+
+// Zerlegt eine Broker-URL wie `mqtt://host:1883/robot1` in Host, Port und Topic-Praefix.
+// Fehlt der Pfad, wird "robot" als Standard-Praefix verwendet.
+fn parse_broker_url(url: &str) -> Option<(String, u16, String)> {
+    let without_scheme = url.strip_prefix("mqtt://")?;
+    let (authority, path) = match without_scheme.split_once('/') {
+        Some((authority, path)) => (authority, path),
+        None => (without_scheme, ""),
+    };
+
+    let (host, port) = match authority.split_once(':') {
+        Some((host, port)) => (host.to_string(), port.parse().ok()?),
+        None => (authority.to_string(), 1883),
+    };
+
+    let prefix = if path.is_empty() {
+        "robot".to_string()
+    } else {
+        path.trim_end_matches('/').to_string()
+    };
+
+    Some((host, port, prefix))
+}
+
+// Verbindet sich mit dem Broker, abonniert `<prefix>/command/#` und leitet jede Nachricht als
+// `CommandPayload` an denselben Kanal weiter, den auch die HTTP-Handler benutzen. Das Topic-Suffix
+// nach `command/` wird als `command`-Feld verwendet, der Payload-Body als `value`. Der
+// Verbindungs-Loop blockiert den aufrufenden Thread, bis der Broker die Verbindung beendet.
+fn run_mqtt_client(
+    mut connection: rumqttc::Connection,
+    prefix: String,
+    command_tx: mpsc::Sender<CommandPayload>,
+) {
+    let command_prefix = format!("{}/command/", prefix);
+
+    for notification in connection.iter() {
+        match notification {
+            Ok(Event::Incoming(Packet::Publish(publish))) => {
+                let Some(command) = publish.topic.strip_prefix(command_prefix.as_str()) else {
+                    warn!("[MQTT] Nachricht auf unerwartetem Topic ignoriert: {}", publish.topic);
+                    continue;
+                };
+
+                let value: serde_json::Value =
+                    serde_json::from_slice(&publish.payload).unwrap_or_else(|_| {
+                        String::from_utf8_lossy(&publish.payload).as_ref().into()
+                    });
+
+                let payload = CommandPayload {
+                    command: command.to_string(),
+                    value,
+                };
+
+                info!("[MQTT] Befehl empfangen: {:?}", payload);
+
+                if let Err(e) = command_tx.send(payload) {
+                    error!("[MQTT] Konnte Befehl nicht an den Hauptthread weiterleiten: {}", e);
+                    break;
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                error!("[MQTT] Verbindungsfehler: {:?}", e);
+                break;
+            }
+        }
+    }
+
+    warn!("[MQTT] Client-Loop beendet.");
+}
+
+// Analog zu `start_axum_server_in_thread`: baut die Verbindung auf, abonniert die Befehls-Topics
+// und startet den (blockierenden) Empfangs-Loop in einem eigenen Thread, damit der Hauptthread
+// unveraendert auf dem `mpsc`-Kanal lauschen kann. Gibt einen `MqttStatePublisher` zurueck, mit
+// dem der Hauptthread angewendete Befehle unter `<prefix>/state/...` zurueckmelden kann.
+pub fn start_mqtt_client_in_thread(
+    broker_url: String,
+    command_tx: mpsc::Sender<CommandPayload>,
+) -> Option<MqttStatePublisher> {
+    let Some((host, port, prefix)) = parse_broker_url(&broker_url) else {
+        error!("[MQTT] Konnte Broker-URL nicht parsen: {}", broker_url);
+        return None;
+    };
+
+    info!(
+        "[MQTT] Verbinde mit Broker {}:{} (Praefix: {})",
+        host, port, prefix
+    );
+
+    let mut mqtt_options = MqttOptions::new("robot_control_system", host, port);
+    mqtt_options.set_keep_alive(Duration::from_secs(5));
+
+    let (client, connection) = Client::new(mqtt_options, 10);
+    let command_topic = format!("{}/command/#", prefix);
+
+    if let Err(e) = client.subscribe(&command_topic, QoS::AtLeastOnce) {
+        error!("[MQTT] Konnte Topic {} nicht abonnieren: {}", command_topic, e);
+        return None;
+    }
+
+    info!("[MQTT] Erstelle neuen Thread fuer MQTT-Client...");
+    let thread_prefix = prefix.clone();
+    std::thread::spawn(move || {
+        run_mqtt_client(connection, thread_prefix, command_tx);
+    });
+
+    Some(MqttStatePublisher { client, prefix })
+}
@@ -0,0 +1,152 @@
+// Optionaler Relay-Client: statt (oder zusaetzlich zu) einem lokalen Listener oeffnet der Roboter
+// eine ausgehende, persistente Verbindung zu einem Rendezvous-/Relay-Server, damit er auch hinter
+// CGNAT/Mobilfunk steuerbar bleibt. Befehle kommen als JSON-Frames ueber den Tunnel und werden in
+// denselben Kanal eingespeist, den auch die lokalen HTTP-Handler benutzen.
+use crate::CommandPayload;
+use futures_util::{SinkExt, StreamExt};
+use log::{error, info, warn};
+use std::sync::mpsc;
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::Message;
+
+// Backoff-Grenzen fuer Reconnect-Versuche: startet kurz, damit eine kurze Netzunterbrechung
+// schnell ueberwunden wird, verdoppelt sich dann bis zu einer Obergrenze, damit ein dauerhaft
+// nicht erreichbarer Relay-Server nicht staendig neu verbunden wird.
+const RELAY_RECONNECT_MIN_DELAY: Duration = Duration::from_secs(1);
+const RELAY_RECONNECT_MAX_DELAY: Duration = Duration::from_secs(30);
+
+// Haelt die ausgehende Relay-Verbindung dauerhaft am Leben: verbindet, authentifiziert und
+// empfaengt Befehle ueber `run_relay_session`; bricht die Verbindung ab (Netzproblem, Broker-Neustart,
+// Relay-Server-Neustart hinter CGNAT/Mobilfunk), wird sie mit wachsendem Backoff neu aufgebaut statt
+// die Fernsteuerung dauerhaft aufzugeben. Blockiert den aufrufenden (Tokio-)Kontext fuer immer, wie
+// auch `run_mqtt_client`/`run_axum_server`.
+async fn run_relay_client(
+    relay_url: String,
+    robot_id: String,
+    token: String,
+    command_tx: mpsc::Sender<CommandPayload>,
+) {
+    // Die Auth-Nachricht enthaelt den Shared-Token im Klartext; ueber ein unverschluesseltes
+    // `ws://` waere er fuer jeden auf dem Pfad mitlesbar. Lieber gar nicht verbinden als den Token
+    // zu leaken.
+    if !relay_url.starts_with("wss://") {
+        error!(
+            "[Relay] relay_url '{}' ist kein wss://, Verbindung wird abgelehnt (Auth-Token wuerde im Klartext uebertragen).",
+            relay_url
+        );
+        return;
+    }
+
+    let mut reconnect_delay = RELAY_RECONNECT_MIN_DELAY;
+
+    loop {
+        let session_connected = run_relay_session(&relay_url, &robot_id, &token, &command_tx).await;
+
+        if session_connected {
+            reconnect_delay = RELAY_RECONNECT_MIN_DELAY;
+        } else {
+            reconnect_delay = std::cmp::min(reconnect_delay * 2, RELAY_RECONNECT_MAX_DELAY);
+        }
+
+        warn!(
+            "[Relay] Verbindung zu {} getrennt, naechster Versuch in {:?}...",
+            relay_url, reconnect_delay
+        );
+        tokio::time::sleep(reconnect_delay).await;
+    }
+}
+
+// Ein einzelner Verbindungs-/Authentifizierungs-/Empfangs-Zyklus. Gibt `true` zurueck, wenn die
+// Verbindung erfolgreich aufgebaut und der Auth-Frame gesendet wurde (unabhaengig davon, wie sie
+// endete), damit der Aufrufer den Reconnect-Backoff nach einer erfolgreichen Session zuruecksetzen
+// kann. Eine Bestaetigung des Relay-Servers fuer die Authentifizierung wird nicht abgewartet.
+async fn run_relay_session(
+    relay_url: &str,
+    robot_id: &str,
+    token: &str,
+    command_tx: &mpsc::Sender<CommandPayload>,
+) -> bool {
+    info!("[Relay] Verbinde ausgehend mit Relay-Server {}...", relay_url);
+
+    let (ws_stream, _response) = match tokio_tungstenite::connect_async(relay_url).await {
+        Ok(connected) => connected,
+        Err(e) => {
+            error!("[Relay] Verbindung zu {} fehlgeschlagen: {}", relay_url, e);
+            return false;
+        }
+    };
+
+    let (mut sender, mut receiver) = ws_stream.split();
+
+    let auth_frame = serde_json::json!({
+        "type": "auth",
+        "robot_id": robot_id,
+        "token": token,
+    });
+    if let Err(e) = sender.send(Message::Text(auth_frame.to_string())).await {
+        error!("[Relay] Konnte Auth-Frame nicht senden: {}", e);
+        return false;
+    }
+
+    info!("[Relay] Verbunden und authentifiziert als '{}'.", robot_id);
+
+    while let Some(msg) = receiver.next().await {
+        let msg = match msg {
+            Ok(msg) => msg,
+            Err(e) => {
+                warn!("[Relay] Fehler beim Empfangen: {}", e);
+                break;
+            }
+        };
+
+        let text = match msg {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+
+        let payload: CommandPayload = match serde_json::from_str(&text) {
+            Ok(payload) => payload,
+            Err(e) => {
+                warn!("[Relay] Konnte Frame nicht als CommandPayload deserialisieren: {}", e);
+                continue;
+            }
+        };
+
+        info!("[Relay] Befehl empfangen: {:?}", payload);
+
+        if let Err(e) = command_tx.send(payload) {
+            error!("[Relay] Konnte Befehl nicht an den Hauptthread weiterleiten: {}", e);
+            break;
+        }
+    }
+
+    warn!("[Relay] Verbindung zu {} beendet.", relay_url);
+    true
+}
+
+// Analog zu `start_axum_server_in_thread`/`start_mqtt_client_in_thread`: startet den Relay-Client
+// in einem eigenen Thread mit eigener Tokio-Runtime, damit lokale und relayte Steuerung
+// nebeneinander laufen koennen und der Hauptthread von der Transport-Wahl unberuehrt bleibt.
+pub fn start_relay_client_in_thread(
+    relay_url: String,
+    robot_id: String,
+    token: String,
+    command_tx: mpsc::Sender<CommandPayload>,
+) {
+    info!("[Relay] Erstelle neuen Thread fuer Relay-Client...");
+
+    let rt = match tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+    {
+        Ok(r) => r,
+        Err(e) => {
+            error!("[Relay] Konnte Tokio Runtime fuer Relay-Thread nicht erstellen: {}", e);
+            return;
+        }
+    };
+
+    rt.block_on(run_relay_client(relay_url, robot_id, token, command_tx));
+    warn!("[Relay] Relay-Client-Thread wurde beendet.");
+}